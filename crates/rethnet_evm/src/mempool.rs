@@ -0,0 +1,314 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, VecDeque},
+};
+
+use rethnet_eth::{Address, B256, U256};
+
+use crate::state::SyncState;
+
+/// A transaction that is pending inclusion in a block.
+#[derive(Clone, Debug)]
+pub struct PendingTransaction {
+    hash: B256,
+    caller: Address,
+    nonce: u64,
+    gas_price: U256,
+    max_priority_fee_per_gas: Option<U256>,
+}
+
+impl PendingTransaction {
+    /// Constructs a new pending transaction.
+    pub fn new(
+        hash: B256,
+        caller: Address,
+        nonce: u64,
+        gas_price: U256,
+        max_priority_fee_per_gas: Option<U256>,
+    ) -> Self {
+        Self {
+            hash,
+            caller,
+            nonce,
+            gas_price,
+            max_priority_fee_per_gas,
+        }
+    }
+
+    /// The transaction's hash.
+    pub fn hash(&self) -> &B256 {
+        &self.hash
+    }
+
+    /// The address that sent the transaction.
+    pub fn caller(&self) -> &Address {
+        &self.caller
+    }
+
+    /// The nonce used to order this transaction relative to others from the same
+    /// sender.
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// The transaction's gas price (`maxFeePerGas`, for EIP-1559 transactions).
+    pub fn gas_price(&self) -> U256 {
+        self.gas_price
+    }
+
+    /// The transaction's `maxPriorityFeePerGas`, for EIP-1559 transactions.
+    pub fn max_priority_fee_per_gas(&self) -> Option<U256> {
+        self.max_priority_fee_per_gas
+    }
+}
+
+/// A [`PendingTransaction`] tagged with the order in which it was inserted into the
+/// mempool, used to break ties when two transactions are otherwise equally ordered.
+#[derive(Clone, Debug)]
+pub struct OrderedTransaction {
+    order_id: u64,
+    transaction: PendingTransaction,
+}
+
+impl OrderedTransaction {
+    /// The insertion order of the transaction.
+    pub fn order_id(&self) -> u64 {
+        self.order_id
+    }
+
+    /// The wrapped transaction.
+    pub fn transaction(&self) -> &PendingTransaction {
+        &self.transaction
+    }
+}
+
+/// The type of comparator used to order pending transactions when mining a block.
+type MineOrderComparator = dyn Fn(&OrderedTransaction, &OrderedTransaction) -> Ordering + Send;
+
+/// The pool of transactions pending inclusion in a block.
+#[derive(Debug)]
+pub struct MemPool {
+    block_gas_limit: U256,
+    transactions: BTreeMap<Address, BTreeMap<u64, PendingTransaction>>,
+    hash_to_caller: HashMap<B256, Address>,
+    /// The order in which each transaction was inserted into the pool.
+    order_ids: HashMap<B256, u64>,
+    next_order_id: u64,
+    /// The number of mining attempts each transaction has been skipped for being
+    /// underpriced (below `min_gas_price` or below the block's base fee).
+    skipped_counts: HashMap<B256, u32>,
+}
+
+impl MemPool {
+    /// Constructs a new, empty mempool with the provided block gas limit.
+    pub fn new(block_gas_limit: U256) -> Self {
+        Self {
+            block_gas_limit,
+            transactions: BTreeMap::new(),
+            hash_to_caller: HashMap::new(),
+            order_ids: HashMap::new(),
+            next_order_id: 0,
+            skipped_counts: HashMap::new(),
+        }
+    }
+
+    /// The gas limit of the next block to be mined.
+    pub fn block_gas_limit(&self) -> &U256 {
+        &self.block_gas_limit
+    }
+
+    /// Adds a transaction to the pool, making it available to future mining attempts.
+    pub fn add_transaction(&mut self, transaction: PendingTransaction) {
+        let hash = *transaction.hash();
+        let caller = *transaction.caller();
+
+        self.transactions
+            .entry(caller)
+            .or_default()
+            .insert(transaction.nonce(), transaction);
+        self.hash_to_caller.insert(hash, caller);
+        self.order_ids.insert(hash, self.next_order_id);
+        self.next_order_id += 1;
+    }
+
+    /// Returns an iterator over the pending transactions, ordered by `comparator`.
+    pub fn iter(&mut self, comparator: Box<MineOrderComparator>) -> PendingTransactions {
+        let mut ordered: Vec<OrderedTransaction> = self
+            .transactions
+            .values()
+            .flat_map(BTreeMap::values)
+            .cloned()
+            .map(|transaction| OrderedTransaction {
+                order_id: self.order_ids[transaction.hash()],
+                transaction,
+            })
+            .collect();
+
+        ordered.sort_by(|lhs, rhs| comparator(lhs, rhs));
+
+        PendingTransactions {
+            remaining: ordered.into_iter().map(|ordered| ordered.transaction).collect(),
+        }
+    }
+
+    /// Increments and returns the number of mining attempts `hash` has been skipped
+    /// for being underpriced.
+    pub fn increment_skipped_count(&mut self, hash: &B256) -> u32 {
+        let count = self.skipped_counts.entry(*hash).or_insert(0);
+        *count += 1;
+        *count
+    }
+
+    /// Permanently removes the transaction with the given hash from the mempool,
+    /// e.g. because it has been skipped for being underpriced too many times.
+    pub fn remove_transaction(&mut self, hash: &B256) {
+        if let Some(caller) = self.hash_to_caller.remove(hash) {
+            if let Some(by_nonce) = self.transactions.get_mut(&caller) {
+                by_nonce.retain(|_, transaction| transaction.hash() != hash);
+
+                if by_nonce.is_empty() {
+                    self.transactions.remove(&caller);
+                }
+            }
+        }
+
+        self.order_ids.remove(hash);
+        self.skipped_counts.remove(hash);
+    }
+
+    /// Permanently removes all of `caller`'s pending transactions from the mempool,
+    /// e.g. because the account no longer exists or has been evicted.
+    pub fn remove_caller(&mut self, caller: &Address) {
+        if let Some(by_nonce) = self.transactions.remove(caller) {
+            for transaction in by_nonce.values() {
+                self.hash_to_caller.remove(transaction.hash());
+                self.order_ids.remove(transaction.hash());
+                self.skipped_counts.remove(transaction.hash());
+            }
+        }
+    }
+
+    /// Updates the mempool based on the latest state, removing transactions that are
+    /// no longer valid (e.g. their nonce has already been used).
+    pub fn update<StateErrorT>(
+        &mut self,
+        state: &dyn SyncState<StateErrorT>,
+    ) -> Result<(), StateErrorT> {
+        let mut stale = Vec::new();
+        for (caller, by_nonce) in &self.transactions {
+            let account_nonce = state.basic(*caller)?.map_or(0, |account| account.nonce);
+            stale.extend(
+                by_nonce
+                    .range(..account_nonce)
+                    .map(|(_, transaction)| *transaction.hash()),
+            );
+        }
+
+        for hash in stale {
+            self.remove_transaction(&hash);
+        }
+
+        Ok(())
+    }
+}
+
+/// An iterator over a snapshot of the mempool's pending transactions, ordered for
+/// inclusion in a block being mined.
+pub struct PendingTransactions {
+    remaining: VecDeque<PendingTransaction>,
+}
+
+impl PendingTransactions {
+    /// Returns the next transaction to attempt to include in the block.
+    pub fn next(&mut self) -> Option<PendingTransaction> {
+        self.remaining.pop_front()
+    }
+
+    /// Excludes `caller`'s remaining transactions from this mining attempt only (e.g.
+    /// because one of their transactions couldn't be included, and the rest can't be
+    /// mined out of nonce order this block). The transactions are left in the
+    /// [`MemPool`] so they can be reconsidered on the next mining attempt; use
+    /// [`MemPool::remove_transaction`] to remove a transaction permanently.
+    pub fn skip_caller(&mut self, caller: &Address) {
+        self.remaining
+            .retain(|transaction| transaction.caller() != caller);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(hash: u8, caller: u8, nonce: u64) -> PendingTransaction {
+        PendingTransaction::new(
+            B256::from([hash; 32]),
+            Address::from([caller; 20]),
+            nonce,
+            U256::from(0),
+            None,
+        )
+    }
+
+    #[test]
+    fn increment_skipped_count_counts_per_transaction() {
+        let mut mem_pool = MemPool::new(U256::from(10_000_000));
+        let hash = B256::from([1; 32]);
+
+        assert_eq!(mem_pool.increment_skipped_count(&hash), 1);
+        assert_eq!(mem_pool.increment_skipped_count(&hash), 2);
+        assert_eq!(mem_pool.increment_skipped_count(&hash), 3);
+
+        // A different transaction's count is tracked independently.
+        let other_hash = B256::from([2; 32]);
+        assert_eq!(mem_pool.increment_skipped_count(&other_hash), 1);
+    }
+
+    #[test]
+    fn remove_transaction_removes_from_pool_and_resets_skip_count() {
+        let mut mem_pool = MemPool::new(U256::from(10_000_000));
+
+        let transaction = transaction(1, 1, 0);
+        let hash = *transaction.hash();
+        let caller = *transaction.caller();
+
+        mem_pool.add_transaction(transaction);
+
+        mem_pool.increment_skipped_count(&hash);
+        mem_pool.remove_transaction(&hash);
+
+        assert!(!mem_pool.transactions.contains_key(&caller));
+        assert_eq!(mem_pool.increment_skipped_count(&hash), 1);
+    }
+
+    #[test]
+    fn transaction_survives_exactly_max_underpriced_blocks_skips_then_is_evicted() {
+        // Mirrors the eviction condition in `mine_block`'s mining loop: a transaction
+        // that is skipped for being underpriced is only evicted once its skip count
+        // exceeds `max_underpriced_blocks`.
+        let mut mem_pool = MemPool::new(U256::from(10_000_000));
+
+        let transaction = transaction(1, 1, 0);
+        let hash = *transaction.hash();
+        let caller = *transaction.caller();
+
+        mem_pool.add_transaction(transaction);
+
+        let max_underpriced_blocks = 3;
+
+        for _ in 0..max_underpriced_blocks {
+            assert!(mem_pool.increment_skipped_count(&hash) <= max_underpriced_blocks);
+        }
+        assert!(
+            mem_pool.transactions.contains_key(&caller),
+            "transaction should survive exactly max_underpriced_blocks skips"
+        );
+
+        // The next skip pushes the count past the threshold, so `mine_block` would
+        // evict it here.
+        assert!(mem_pool.increment_skipped_count(&hash) > max_underpriced_blocks);
+        mem_pool.remove_transaction(&hash);
+
+        assert!(!mem_pool.transactions.contains_key(&caller));
+        assert!(!mem_pool.hash_to_caller.contains_key(&hash));
+    }
+}
@@ -4,12 +4,12 @@ use rethnet_eth::{
     block::{BlockOptions, Header},
     Address, B256, B64, U256,
 };
-use revm::primitives::{CfgEnv, ExecutionResult, InvalidTransaction, SpecId};
+use revm::primitives::{CfgEnv, ExecutionResult, InvalidTransaction, SpecId, KECCAK_EMPTY};
 
 use crate::{
     block::BlockBuilderCreationError,
     blockchain::SyncBlockchain,
-    mempool::OrderedTransaction,
+    mempool::{OrderedTransaction, PendingTransactions},
     state::SyncState,
     trace::{Trace, TraceCollector},
     BlockBuilder, BlockTransactionError, BuildBlockResult, MemPool, PendingTransaction, SyncBlock,
@@ -25,6 +25,27 @@ pub struct MineBlockResult<BlockchainErrorT, StateErrorT> {
     pub transaction_results: Vec<ExecutionResult>,
     /// Transaction traces
     pub transaction_traces: Vec<Trace>,
+    /// The effective gas price charged to each transaction, in the same order as
+    /// `transaction_results`.
+    pub effective_gas_prices: Vec<U256>,
+    /// Pending transactions that were skipped during mining for one of the reasons in
+    /// [`SkippedTransactionReason`], along with that reason. Transactions skipped for
+    /// being below the base fee or for exceeding the block gas limit are not listed.
+    pub skipped_transactions: Vec<(B256, SkippedTransactionReason)>,
+    /// Transactions that were permanently removed from the mempool for being
+    /// underpriced across too many mining attempts.
+    pub evicted_transactions: Vec<B256>,
+}
+
+/// The reason a pending transaction was skipped while mining a block, rather than being
+/// included.
+#[derive(Debug, Clone, Copy)]
+pub enum SkippedTransactionReason {
+    /// The transaction's gas price is below the miner's configured minimum.
+    GasPriceTooLow,
+    /// The transaction's sender account has deployed code, which EIP-3607 forbids from
+    /// originating transactions.
+    SenderHasCode,
 }
 
 /// The type of ordering to use when selecting blocks to mine.
@@ -60,6 +81,57 @@ pub enum MineBlockError<BE, SE> {
     /// The block is expected to have a prevrandao, as the executor's config is on a post-merge hardfork.
     #[error("Post-merge transaction is missing prevrandao")]
     MissingPrevrandao,
+    /// An error that occurred while retrieving a sender's account state.
+    #[error(transparent)]
+    State(SE),
+}
+
+/// Parameters for the EIP-1559 base fee adjustment algorithm.
+#[derive(Debug, Clone, Copy)]
+pub struct BaseFeeParams {
+    /// The gas target elasticity multiplier, i.e. the factor by which the gas limit
+    /// exceeds the gas target.
+    pub elasticity_multiplier: U256,
+    /// The denominator that bounds how much the base fee can change from one block to
+    /// the next.
+    pub max_change_denominator: U256,
+}
+
+impl Default for BaseFeeParams {
+    /// The base fee parameters used by Ethereum mainnet since the London hardfork.
+    fn default() -> Self {
+        Self {
+            elasticity_multiplier: U256::from(2),
+            max_change_denominator: U256::from(8),
+        }
+    }
+}
+
+/// The base fee of the first post-London block, used when the parent block predates
+/// London and therefore has no base fee of its own.
+const INITIAL_BASE_FEE: u64 = 1_000_000_000;
+
+/// The hardfork at which EIP-3607 (rejecting transactions from senders with deployed
+/// code) was activated on mainnet. This happens to coincide with the London hardfork's
+/// EIP-1559 activation, but is tracked separately since the two EIPs are unrelated.
+const EIP_3607_ACTIVATION: SpecId = SpecId::LONDON;
+
+/// Configuration for how `mine_block` deals with transactions that repeatedly fail to
+/// be included.
+#[derive(Debug, Clone, Copy)]
+pub struct MineBlockConfig {
+    /// The number of mining attempts a transaction may be skipped for being
+    /// underpriced (below `min_gas_price` or below the base fee) before it is
+    /// permanently removed from the mempool.
+    pub max_underpriced_blocks: u32,
+}
+
+impl Default for MineBlockConfig {
+    fn default() -> Self {
+        Self {
+            max_underpriced_blocks: 25,
+        }
+    }
 }
 
 /// Mines a block using as many transactions as can fit in it.
@@ -70,11 +142,13 @@ pub async fn mine_block<BlockchainErrorT, StateErrorT>(
     mut state: Box<dyn SyncState<StateErrorT>>,
     mem_pool: &mut MemPool,
     cfg: &CfgEnv,
+    mine_block_config: &MineBlockConfig,
     timestamp: U256,
     beneficiary: Address,
     min_gas_price: U256,
     mine_ordering: MineOrdering,
     reward: U256,
+    base_fee_params: BaseFeeParams,
     base_fee: Option<U256>,
     prevrandao: Option<B256>,
 ) -> Result<
@@ -92,7 +166,7 @@ where
 
     let parent_header = parent_block.header();
     let base_fee = if cfg.spec_id >= SpecId::LONDON {
-        Some(base_fee.unwrap_or_else(|| calculate_next_base_fee(parent_header)))
+        Some(base_fee.unwrap_or_else(|| calculate_next_base_fee(parent_header, base_fee_params)))
     } else {
         None
     };
@@ -157,25 +231,82 @@ where
 
     let mut results = Vec::new();
     let mut traces = Vec::new();
+    let mut effective_gas_prices = Vec::new();
+    let mut skipped_transactions = Vec::new();
+    let mut evicted_transactions = Vec::new();
 
     while let Some(transaction) = pending_transactions.next() {
         let mut tracer = TraceCollector::default();
 
         if transaction.gas_price() < min_gas_price {
-            pending_transactions.remove_caller(transaction.caller());
+            let hash = *transaction.hash();
+            let caller = *transaction.caller();
+            skipped_transactions.push((hash, SkippedTransactionReason::GasPriceTooLow));
+
+            skip_or_evict_underpriced(
+                mem_pool,
+                &mut pending_transactions,
+                &mut evicted_transactions,
+                &caller,
+                hash,
+                mine_block_config.max_underpriced_blocks,
+            );
             continue;
         }
 
         let caller = *transaction.caller();
+        let transaction_hash = *transaction.hash();
+
+        // EIP-3607 (reject transactions from senders with deployed code) was activated
+        // in the London hardfork, the same fork as EIP-1559's base fee above, but the
+        // two checks are independent: this gate tracks EIP-3607's own activation, not
+        // the presence of a base fee.
+        if cfg.spec_id >= EIP_3607_ACTIVATION {
+            let sender = state
+                .basic(caller)
+                .map_err(MineBlockError::State)?
+                .unwrap_or_default();
+
+            if sender.code_hash != KECCAK_EMPTY {
+                skipped_transactions
+                    .push((*transaction.hash(), SkippedTransactionReason::SenderHasCode));
+                pending_transactions.skip_caller(&caller);
+                continue;
+            }
+        }
+
+        // Transactions below the base fee are rejected by `add_transaction` below (and
+        // tracked for eviction), so `gas_price` may legitimately be less than `base_fee`
+        // here; use a saturating subtraction to avoid panicking on those.
+        let effective_gas_price = match base_fee {
+            Some(base_fee) => {
+                let max_priority_fee_per_gas = transaction
+                    .max_priority_fee_per_gas()
+                    .unwrap_or_else(|| transaction.gas_price());
+
+                base_fee
+                    + max_priority_fee_per_gas.min(transaction.gas_price().saturating_sub(base_fee))
+            }
+            None => transaction.gas_price(),
+        };
+
         match block_builder.add_transaction(blockchain, &mut state, transaction, Some(&mut tracer))
         {
-            Err(
-                BlockTransactionError::ExceedsBlockGasLimit
-                | BlockTransactionError::InvalidTransaction(
-                    InvalidTransaction::GasPriceLessThanBasefee,
-                ),
-            ) => {
-                pending_transactions.remove_caller(&caller);
+            Err(BlockTransactionError::ExceedsBlockGasLimit) => {
+                pending_transactions.skip_caller(&caller);
+                continue;
+            }
+            Err(BlockTransactionError::InvalidTransaction(
+                InvalidTransaction::GasPriceLessThanBasefee,
+            )) => {
+                skip_or_evict_underpriced(
+                    mem_pool,
+                    &mut pending_transactions,
+                    &mut evicted_transactions,
+                    &caller,
+                    transaction_hash,
+                    mine_block_config.max_underpriced_blocks,
+                );
                 continue;
             }
             Err(e) => {
@@ -184,6 +315,7 @@ where
             Ok(result) => {
                 results.push(result);
                 traces.push(tracer.into_trace());
+                effective_gas_prices.push(effective_gas_price);
             }
         }
     }
@@ -207,22 +339,225 @@ where
         state,
         transaction_results: results,
         transaction_traces: traces,
+        effective_gas_prices,
+        skipped_transactions,
+        evicted_transactions,
     })
 }
 
+/// The result of computing the fee history for a range of blocks.
+#[derive(Debug)]
+pub struct FeeHistoryResult {
+    /// Lowest number block of the returned range.
+    pub oldest_block: U256,
+    /// An array of block base fees per gas, including the next block after the newest
+    /// of the returned range.
+    pub base_fee_per_gas: Vec<U256>,
+    /// An array of block gas used ratios.
+    pub gas_used_ratio: Vec<f64>,
+    /// An array of effective priority fee per gas data, present only if
+    /// `reward_percentiles` was provided.
+    pub reward: Option<Vec<Vec<U256>>>,
+}
+
+/// An error that occurred while computing a fee history.
+#[derive(Debug, thiserror::Error)]
+pub enum FeeHistoryError<BlockchainErrorT> {
+    /// A blockchain error
+    #[error(transparent)]
+    Blockchain(BlockchainErrorT),
+    /// The requested block does not exist.
+    #[error("Block {0} does not exist")]
+    UnknownBlockNumber(U256),
+}
+
+/// Computes the data backing `eth_feeHistory` for `block_count` blocks up to and
+/// including `newest_block`. A `newest_block` past the chain's last block is treated
+/// as the last block.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+pub async fn fee_history<BlockchainErrorT, StateErrorT>(
+    blockchain: &mut dyn SyncBlockchain<BlockchainErrorT, StateErrorT>,
+    block_count: u64,
+    newest_block: U256,
+    reward_percentiles: Option<Vec<f64>>,
+) -> Result<FeeHistoryResult, FeeHistoryError<BlockchainErrorT>>
+where
+    BlockchainErrorT: Debug + Send + 'static,
+    StateErrorT: Debug + Send + 'static,
+{
+    let block_count = block_count.clamp(1, 1024);
+
+    let last_block_number = blockchain
+        .last_block()
+        .await
+        .map_err(FeeHistoryError::Blockchain)?
+        .header()
+        .number;
+
+    let newest_block = newest_block.min(last_block_number);
+    // A `block_count` that reaches past genesis simply starts the range there instead.
+    let oldest_block = newest_block.saturating_sub(U256::from(block_count - 1));
+
+    let mut base_fee_per_gas = Vec::new();
+    let mut gas_used_ratio = Vec::new();
+    let mut reward = reward_percentiles.as_ref().map(|percentiles| {
+        debug_assert!(percentiles.windows(2).all(|pair| pair[0] <= pair[1]));
+        Vec::new()
+    });
+
+    let mut block_number = oldest_block;
+    let mut last_header = None;
+    while block_number <= newest_block {
+        let block = blockchain
+            .block_by_number(&block_number)
+            .await
+            .map_err(FeeHistoryError::Blockchain)?
+            .ok_or(FeeHistoryError::UnknownBlockNumber(block_number))?;
+
+        let header = block.header();
+
+        base_fee_per_gas.push(header.base_fee_per_gas.unwrap_or(U256::ZERO));
+        gas_used_ratio.push(as_f64(header.gas_used) / as_f64(header.gas_limit));
+
+        if let (Some(percentiles), Some(reward)) = (&reward_percentiles, &mut reward) {
+            reward.push(
+                block_rewards(
+                    blockchain,
+                    &block,
+                    header.base_fee_per_gas,
+                    header.gas_used,
+                    percentiles,
+                )
+                .await?,
+            );
+        }
+
+        last_header = Some(header.clone());
+        block_number += U256::from(1);
+    }
+
+    // The final entry is the base fee of the block after `newest_block`.
+    let last_header = last_header.expect("at least one block was collected");
+    base_fee_per_gas.push(calculate_next_base_fee(
+        &last_header,
+        BaseFeeParams::default(),
+    ));
+
+    Ok(FeeHistoryResult {
+        oldest_block,
+        base_fee_per_gas,
+        gas_used_ratio,
+        reward,
+    })
+}
+
+/// Computes the reward (effective priority fee per gas) paid at each of `percentiles` for
+/// a single block.
+async fn block_rewards<BlockchainErrorT, StateErrorT>(
+    blockchain: &mut dyn SyncBlockchain<BlockchainErrorT, StateErrorT>,
+    block: &Arc<dyn SyncBlock<Error = BlockchainErrorT>>,
+    base_fee_per_gas: Option<U256>,
+    block_gas_used: U256,
+    percentiles: &[f64],
+) -> Result<Vec<U256>, FeeHistoryError<BlockchainErrorT>>
+where
+    BlockchainErrorT: Debug + Send + 'static,
+    StateErrorT: Debug + Send + 'static,
+{
+    let base_fee = base_fee_per_gas.unwrap_or(U256::ZERO);
+
+    let receipts = blockchain
+        .receipts_by_block_hash(block.hash())
+        .await
+        .map_err(FeeHistoryError::Blockchain)?
+        .unwrap_or_default();
+
+    // `cumulative_gas_used` is the yellow paper's cumulative gas used by the block up
+    // to and including that transaction, not the transaction's own gas usage; recover
+    // the per-transaction amount as the delta from the previous receipt.
+    let mut previous_cumulative_gas_used = U256::ZERO;
+    let mut tips: Vec<(U256, U256)> = block
+        .transactions()
+        .iter()
+        .zip(receipts.iter())
+        .map(|(transaction, receipt)| {
+            let max_priority_fee_per_gas = transaction
+                .max_priority_fee_per_gas()
+                .unwrap_or_else(|| transaction.gas_price());
+
+            let tip = max_priority_fee_per_gas
+                .min(transaction.gas_price().saturating_sub(base_fee));
+
+            let transaction_gas_used = receipt.cumulative_gas_used - previous_cumulative_gas_used;
+            previous_cumulative_gas_used = receipt.cumulative_gas_used;
+
+            (tip, transaction_gas_used)
+        })
+        .collect();
+
+    if tips.is_empty() || block_gas_used == U256::ZERO {
+        return Ok(percentiles.iter().map(|_| U256::ZERO).collect());
+    }
+
+    tips.sort_by_key(|(tip, _)| *tip);
+
+    Ok(percentiles
+        .iter()
+        .map(|percentile| {
+            let threshold = block_gas_used * U256::from((percentile * 100.0) as u64)
+                / U256::from(10_000);
+
+            let mut cumulative_gas_used = U256::ZERO;
+            for (tip, gas_used) in &tips {
+                cumulative_gas_used += *gas_used;
+                if cumulative_gas_used >= threshold {
+                    return *tip;
+                }
+            }
+
+            tips.last().expect("checked non-empty above").0
+        })
+        .collect())
+}
+
+/// Converts a gas quantity to an `f64` for ratio calculations.
+fn as_f64(value: U256) -> f64 {
+    value.to::<u64>() as f64
+}
+
+/// Tracks a transaction that was skipped during mining for being underpriced (below
+/// `min_gas_price` or below the block's base fee). Once it has been skipped on more
+/// than `max_underpriced_blocks` mining attempts it is evicted from the mempool
+/// entirely and recorded in `evicted_transactions`; otherwise it is merely excluded
+/// from this mining attempt, leaving it in the mempool to be reconsidered next time.
+fn skip_or_evict_underpriced(
+    mem_pool: &mut MemPool,
+    pending_transactions: &mut PendingTransactions,
+    evicted_transactions: &mut Vec<B256>,
+    caller: &Address,
+    hash: B256,
+    max_underpriced_blocks: u32,
+) {
+    if mem_pool.increment_skipped_count(&hash) > max_underpriced_blocks {
+        mem_pool.remove_transaction(&hash);
+        evicted_transactions.push(hash);
+    } else {
+        pending_transactions.skip_caller(caller);
+    }
+}
+
 /// Calculates the next base fee for a post-London block, given the parent's header.
 ///
-/// # Panics
-///
-/// Panics if the parent header does not contain a base fee.
-fn calculate_next_base_fee(parent: &Header) -> U256 {
-    let elasticity = U256::from(2);
-    let base_fee_max_change_denominator = U256::from(8);
+/// If the parent header does not contain a base fee, the parent predates the London
+/// hardfork and `INITIAL_BASE_FEE` is returned, matching the base fee of the first
+/// post-London block.
+fn calculate_next_base_fee(parent: &Header, params: BaseFeeParams) -> U256 {
+    let parent_base_fee = match parent.base_fee_per_gas {
+        Some(base_fee) => base_fee,
+        None => return U256::from(INITIAL_BASE_FEE),
+    };
 
-    let parent_gas_target = parent.gas_limit / elasticity;
-    let parent_base_fee = parent
-        .base_fee_per_gas
-        .expect("Post-London headers must contain a baseFee");
+    let parent_gas_target = parent.gas_limit / params.elasticity_multiplier;
 
     match parent.gas_used.cmp(&parent_gas_target) {
         std::cmp::Ordering::Less => {
@@ -230,7 +565,7 @@ fn calculate_next_base_fee(parent: &Header) -> U256 {
 
             let delta = parent_base_fee * gas_used_delta
                 / parent_gas_target
-                / base_fee_max_change_denominator;
+                / params.max_change_denominator;
 
             parent_base_fee.saturating_sub(delta)
         }
@@ -240,7 +575,7 @@ fn calculate_next_base_fee(parent: &Header) -> U256 {
 
             let delta = parent_base_fee * gas_used_delta
                 / parent_gas_target
-                / base_fee_max_change_denominator;
+                / params.max_change_denominator;
 
             parent_base_fee + delta.max(U256::from(1))
         }
@@ -284,8 +619,88 @@ mod tests {
 
             assert_eq!(
                 U256::from(next_base_fee),
-                calculate_next_base_fee(&parent_header)
+                calculate_next_base_fee(&parent_header, BaseFeeParams::default())
+            );
+        }
+    }
+
+    #[test]
+    fn test_calculate_next_base_fee_pre_london_parent() {
+        // A parent header with no base fee means the parent predates the London
+        // hardfork, so the next (first post-London) block should get `INITIAL_BASE_FEE`
+        // rather than panicking.
+        let parent_header = Header {
+            base_fee_per_gas: None,
+            gas_used: U256::from(10_000_000),
+            gas_limit: U256::from(10_000_000),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            U256::from(INITIAL_BASE_FEE),
+            calculate_next_base_fee(&parent_header, BaseFeeParams::default())
+        );
+    }
+
+    fn fifo_comparator() -> Box<dyn Fn(&OrderedTransaction, &OrderedTransaction) -> Ordering + Send>
+    {
+        Box::new(|lhs, rhs| lhs.order_id().cmp(&rhs.order_id()))
+    }
+
+    #[test]
+    fn skip_or_evict_underpriced_evicts_only_after_max_underpriced_blocks() {
+        // Drives the same `skip_or_evict_underpriced` helper that `mine_block`'s
+        // mining loop calls, over a real `MemPool`, simulating repeated mining
+        // attempts against a transaction that stays underpriced.
+        let hash = B256::from([7; 32]);
+        let caller = Address::from([9; 20]);
+
+        let mut mem_pool = MemPool::new(U256::from(10_000_000));
+        mem_pool.add_transaction(PendingTransaction::new(hash, caller, 0, U256::from(1), None));
+
+        let max_underpriced_blocks = 3;
+        let mut evicted_transactions = Vec::new();
+
+        for attempt in 0..max_underpriced_blocks {
+            let mut pending_transactions = mem_pool.iter(fifo_comparator());
+            let transaction = pending_transactions
+                .next()
+                .unwrap_or_else(|| panic!("transaction missing on attempt {attempt}"));
+
+            skip_or_evict_underpriced(
+                &mut mem_pool,
+                &mut pending_transactions,
+                &mut evicted_transactions,
+                &caller,
+                *transaction.hash(),
+                max_underpriced_blocks,
+            );
+
+            assert!(
+                evicted_transactions.is_empty(),
+                "must not evict before exceeding max_underpriced_blocks"
             );
         }
+
+        // One more attempt pushes the skip count past the threshold.
+        let mut pending_transactions = mem_pool.iter(fifo_comparator());
+        let transaction = pending_transactions
+            .next()
+            .expect("transaction should still be pending before the final attempt");
+
+        skip_or_evict_underpriced(
+            &mut mem_pool,
+            &mut pending_transactions,
+            &mut evicted_transactions,
+            &caller,
+            *transaction.hash(),
+            max_underpriced_blocks,
+        );
+
+        assert_eq!(evicted_transactions, vec![hash]);
+
+        // The transaction is now gone from the mempool entirely.
+        let mut pending_transactions = mem_pool.iter(fifo_comparator());
+        assert!(pending_transactions.next().is_none());
     }
 }
\ No newline at end of file